@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
@@ -7,14 +8,237 @@ use indicatif::{ProgressBar, ProgressStyle};
 use std::thread;
 use std::sync::mpsc;
 use std::time::Instant;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 use structopt::StructOpt;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::Match;
+use rayon::prelude::*;
+use filetime::FileTime;
 
 #[derive(StructOpt)]
 struct Args {
     source: String,
     target: String,
     threads: u32,
+
+    /// Glob pattern to exclude from the copy; may be repeated
+    #[structopt(long = "exclude")]
+    exclude: Vec<String>,
+
+    /// Disable `.gitignore`/`.ignore` filtering
+    #[structopt(long = "no-ignore")]
+    no_ignore: bool,
+
+    /// How to handle files that already exist at the destination:
+    /// `skip`, `overwrite`, or `update` (recopy only if newer/different size)
+    #[structopt(long = "on-conflict", default_value = "skip")]
+    on_conflict: ConflictMode,
+
+    /// Restore source mtime/atime on copied files and recreate symlinks
+    /// instead of copying their target's contents
+    #[structopt(long = "preserve")]
+    preserve: bool,
+}
+
+/// Decides what happens when a destination file already exists.
+#[derive(Clone, Copy, PartialEq)]
+enum ConflictMode {
+    Skip,
+    Overwrite,
+    Update,
+}
+
+impl std::str::FromStr for ConflictMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ConflictMode::Skip),
+            "overwrite" => Ok(ConflictMode::Overwrite),
+            "update" => Ok(ConflictMode::Update),
+            other => Err(format!("Unknown --on-conflict mode: {}", other)),
+        }
+    }
+}
+
+/// Decides whether `src` should be (re)copied over `dst`, given the
+/// destination already exists, based on the chosen conflict mode.
+fn should_copy(mode: ConflictMode, src_meta: &fs::Metadata, dst_meta: &fs::Metadata) -> bool {
+    match mode {
+        ConflictMode::Skip => false,
+        ConflictMode::Overwrite => true,
+        ConflictMode::Update => {
+            if src_meta.len() != dst_meta.len() {
+                return true;
+            }
+            match (src_meta.modified(), dst_meta.modified()) {
+                (Ok(src_time), Ok(dst_time)) => src_time > dst_time,
+                _ => true,
+            }
+        }
+    }
+}
+
+/// Recreates a symlink at `dst` pointing at `target`, rather than copying
+/// the contents the link resolves to.
+#[cfg(unix)]
+fn create_symlink(target: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, dst)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(target: &Path, dst: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!("symlinks are not supported on this platform ({:?} -> {:?})", target, dst),
+    ))
+}
+
+/// Restores `src_meta`'s mtime/atime on `dst` so a `--preserve` copy is a
+/// faithful mirror rather than just a byte-for-byte copy.
+fn preserve_timestamps(src_meta: &fs::Metadata, dst: &Path) {
+    let atime = FileTime::from_last_access_time(src_meta);
+    let mtime = FileTime::from_last_modification_time(src_meta);
+    if let Err(e) = filetime::set_file_times(dst, atime, mtime) {
+        eprintln!("Failed to preserve timestamps on {:?}: {}", dst, e);
+    }
+}
+
+/// A single file's copy outcome, reported over the progress channel so the
+/// listener thread can track both throughput and the file most recently finished.
+struct ProgressUpdate {
+    bytes: u64,
+    file: PathBuf,
+    outcome: CopyOutcome,
+}
+
+/// What happened to a single file, reported alongside every `ProgressUpdate`
+/// so the listener thread can account for every file exactly once.
+#[derive(Clone, Copy, PartialEq)]
+enum CopyOutcome {
+    Copied,
+    Skipped,
+    Failed,
+}
+
+/// Accumulates `.gitignore`/`.ignore` rules while descending through the
+/// source tree, so that nested ignore files can override their parents.
+///
+/// Each frame is tagged with the depth of the directory it came from, since
+/// not every directory has its own ignore file: popping by comparing
+/// `stack.len()` to the current depth would leak a directory's rules into an
+/// ignore-file-less sibling subtree at the same depth.
+struct IgnoreMatcher {
+    root: PathBuf,
+    stack: Vec<(usize, Gitignore)>,
+    excludes: Override,
+}
+
+impl IgnoreMatcher {
+    fn new(root: &Path, excludes: &[String]) -> Self {
+        let mut builder = OverrideBuilder::new(root);
+        for pattern in excludes {
+            // `ignore::overrides::OverrideBuilder` inverts gitignore semantics: an
+            // un-prefixed pattern is a whitelist entry, so a `--exclude` glob needs
+            // a leading `!` to actually behave like an exclude.
+            let negated = format!("!{}", pattern);
+            if let Err(e) = builder.add(&negated) {
+                eprintln!("Invalid --exclude pattern {:?}: {}", pattern, e);
+                std::process::exit(1);
+            }
+        }
+        let excludes = match builder.build() {
+            Ok(excludes) => excludes,
+            Err(e) => {
+                eprintln!("Failed to build exclude matcher: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        IgnoreMatcher {
+            root: root.to_path_buf(),
+            stack: Vec::new(),
+            excludes,
+        }
+    }
+
+    /// Loads the `.gitignore`/`.ignore` files (if any) of `dir` and pushes
+    /// them on top of the stack, tagged with `depth`, so they take
+    /// precedence over ancestors while descending into `dir`'s children.
+    fn push_dir(&mut self, dir: &Path, depth: usize) {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut has_rules = false;
+        for name in [".gitignore", ".ignore"] {
+            let candidate = dir.join(name);
+            if candidate.is_file() {
+                if let Some(err) = builder.add(&candidate) {
+                    eprintln!("Failed to parse {}: {}", candidate.display(), err);
+                } else {
+                    has_rules = true;
+                }
+            }
+        }
+
+        if has_rules {
+            match builder.build() {
+                Ok(gitignore) => self.stack.push((depth, gitignore)),
+                Err(e) => eprintln!("Failed to build ignore rules for {}: {}", dir.display(), e),
+            }
+        }
+    }
+
+    /// Drops frames belonging to directories we've walked back out of, i.e.
+    /// everything at or below `depth` (an entry only inherits rules from
+    /// strict ancestors, not from its own directory).
+    fn pop_to(&mut self, depth: usize) {
+        self.stack.retain(|(frame_depth, _)| *frame_depth < depth);
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if self.excludes.matched(relative, is_dir).is_whitelist() {
+            return false;
+        }
+        if self.excludes.matched(relative, is_dir).is_ignore() {
+            return true;
+        }
+
+        for (_, gitignore) in self.stack.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => continue,
+            }
+        }
+
+        false
+    }
+}
+
+/// Walks `root`, honoring `.gitignore`/`.ignore` rules (unless `no_ignore`
+/// is set) and any `--exclude` globs, loading nested ignore files as the
+/// walk descends so that they can override their parents.
+fn walk_filtered(root: &str, excludes: &[String], no_ignore: bool) -> impl Iterator<Item = DirEntry> {
+    let matcher = RefCell::new(IgnoreMatcher::new(Path::new(root), excludes));
+
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(move |entry| {
+            let mut matcher = matcher.borrow_mut();
+            matcher.pop_to(entry.depth());
+
+            let is_dir = entry.file_type().is_dir();
+            let ignored = matcher.is_ignored(entry.path(), is_dir);
+
+            if !ignored && is_dir && !no_ignore {
+                matcher.push_dir(entry.path(), entry.depth());
+            }
+
+            !ignored
+        })
+        .filter_map(Result::ok)
 }
 
 fn main() {
@@ -23,6 +247,10 @@ fn main() {
     let source = &args.source;
     let target = &args.target;
     let thread_count = &args.threads;
+    let excludes = &args.exclude;
+    let on_conflict = args.on_conflict;
+    let no_ignore = args.no_ignore;
+    let preserve = args.preserve;
 
     // Convert source and target paths to PathBuf for easier manipulation
     let source_path = Path::new(source).to_path_buf();
@@ -44,9 +272,9 @@ fn main() {
 
     // Count total files and directories in the source
     println!("Gathering folder structure...");
-    let (total_files, _total_size) = count_files_in_dir(source);
-    let folders = get_directories(source);
-    let files: Vec<PathBuf> = get_files(source);
+    let (total_files, total_size) = count_files_in_dir(source, excludes, no_ignore);
+    let folders = get_directories(source, excludes, no_ignore);
+    let files: Vec<PathBuf> = get_files(source, excludes, no_ignore);
 
     // Print total files and directories
     println!("Total files / folders: {:?} / {:?}", total_files, folders.len());
@@ -60,63 +288,54 @@ fn main() {
     println!("Created all folders in destination.");
 
     // Set up progress bar for user feedback
-    let (tx, _rx): (mpsc::Sender<u64>, mpsc::Receiver<u64>) = mpsc::channel();
-    let pb = ProgressBar::new(total_files as u64);
+    let (tx, _rx): (mpsc::Sender<ProgressUpdate>, mpsc::Receiver<ProgressUpdate>) = mpsc::channel();
+    let pb = ProgressBar::new(total_size);
     pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:80.cyan/blue}] {pos}/{len} ({eta}) {percent}% {msg}")
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}")
         .expect("Fail to set ProgressStyle template")
         .progress_chars("#>-"));
-        
+
     let pb = Arc::new(Mutex::new(pb));
     let pb_clone = Arc::clone(&pb);
 
-    // Spawn a thread to listen for progress updates and update the progress bar
+    // Spawn a thread to listen for progress updates and update the progress bar.
+    // `_rx.recv()` returns `Err` once every `tx` clone has been dropped, which is
+    // what ends this loop; it does not count up to `total_files`, so it also
+    // terminates if fewer messages arrive than expected.
     let _pb_thread = thread::spawn(move || {
-        let mut _received_count = 0 as u64;
-
-        while _received_count < total_files as u64 {
-            let mut _received = 0;
-            match _rx.recv() {
-                Ok(message) => {
-                    _received = message;
-                    _received_count += 1;   
-                    let pb = pb_clone.lock().unwrap();     
-                    pb.inc(1);
-                }
-                Err(e) => {
-                    if e.to_string() != "receiving on an empty channel" {
-                        eprintln!("RX receive error: {}", e)
-                    }
-                }
+        let mut copied = 0u64;
+        let mut skipped = 0u64;
+        let mut failed = 0u64;
+
+        while let Ok(update) = _rx.recv() {
+            match update.outcome {
+                CopyOutcome::Copied => copied += 1,
+                CopyOutcome::Skipped => skipped += 1,
+                CopyOutcome::Failed => failed += 1,
             }
+
+            let pb = pb_clone.lock().unwrap();
+            pb.inc(update.bytes);
+            pb.set_message(format!("{}", update.file.display()));
         }
 
-        let msg = format!("\n{}/{} files copied\n", _received_count, total_files);
+        let msg = format!("\n{} copied, {} skipped, {} failed\n", copied, skipped, failed);
         let pb = pb.lock().unwrap();
         pb.finish_with_message(msg);
     });
 
-    // Calculate chunk size for parallel processing
-    let chunk_size_fp64 = (total_files / *thread_count as u64) as f64;
-    let chunk_size = chunk_size_fp64.round() as usize;
-    let chunks: Vec<_> = files.chunks(chunk_size).collect();
-    let _thread_num = chunks.len();
-
     // Configure and start the global thread pool for parallel file copying
     rayon::ThreadPoolBuilder::new().num_threads(*thread_count as usize).build_global().unwrap();
 
-    // Spawn threads to copy files in parallel
-    rayon::scope(|s| {
-        for chunk in chunks {
-            let source_path = source_path.clone();
-            let target_path = target_path.clone();
-            let tx = tx.clone();
-
-            s.spawn(move |_| {
-                copy_folder(&source_path, &target_path, tx, &chunk);
-            });
-        }
-    });
+    // Copy every file through a single rayon parallel iterator instead of
+    // pre-splitting into fixed-size chunks. Since file sizes are wildly
+    // uneven, a fixed chunking scheme can hand one worker all the large
+    // files while others sit idle; rayon's work-stealing keeps every thread
+    // pulling from the same pool of remaining files until it's empty.
+    // `copy_folder` takes ownership of `tx`, so every clone handed to the
+    // parallel iterator is dropped by the time it returns, closing the
+    // channel and letting the listener thread above finish.
+    copy_folder(&source_path, &target_path, tx, &files, on_conflict, preserve);
 
     // Wait for all threads to finish and the progress bar to complete
     let _= _pb_thread.join().unwrap();
@@ -131,57 +350,109 @@ fn main() {
 }
 
 // Function to copy a folder's contents from source to target
-fn copy_folder(source: &Path, target: &Path, tx: mpsc::Sender<u64>, files: &[PathBuf]) {
+fn copy_folder(source: &Path, target: &Path, tx: mpsc::Sender<ProgressUpdate>, files: &[PathBuf], on_conflict: ConflictMode, preserve: bool) {
 
-    for file in files {
+    files.par_iter().for_each_with(tx, |tx, file| {
         // Ensure the source path is a subdirectory of the target path
         let is_valid_src_path = file.starts_with(source);
         if !is_valid_src_path {
             eprintln!("Source path is not a subdirectory of the target path");
-            continue;
+            tx.send(ProgressUpdate { bytes: 0, file: file.clone(), outcome: CopyOutcome::Failed })
+                .expect("Failed to send message through the channel");
+            return;
         }
 
         // Calculate relative path (clone current_source to avoid move)
         let relative_path = file.strip_prefix(source).expect("Failed to strip prefix");
         let dst_path = target.join(relative_path);
 
-        // check if the destination file is existed.
-        let mut _existed_dst_file: bool = false;
-        match fs::metadata(dst_path.clone()) {
-            Ok(_metadata) => {
-                _existed_dst_file = true;
+        // With `--preserve`, symlinks are recreated as links rather than copied as
+        // regular files, so this is handled before any of the conflict/copy logic below.
+        let src_link_meta = fs::symlink_metadata(file);
+        let is_symlink = src_link_meta.as_ref().map(|m| m.file_type().is_symlink()).unwrap_or(false);
+
+        if is_symlink && preserve {
+            // `count_files_in_dir` sums `symlink_metadata(...).len()` (the byte length of
+            // the link text itself) into `total_size` for symlinks, so report the same
+            // figure here rather than 0, or the byte-progress bar would never complete.
+            let bytes = src_link_meta.as_ref().map(|m| m.len()).unwrap_or(0);
+            let dst_link_meta = fs::symlink_metadata(&dst_path);
+            let dst_exists = dst_link_meta.is_ok();
+            let needs_copy = match (src_link_meta.as_ref(), dst_link_meta.as_ref()) {
+                (Ok(src_meta), Ok(dst_meta)) => should_copy(on_conflict, src_meta, dst_meta),
+                _ => true,
+            };
+            if !needs_copy {
+                tx.send(ProgressUpdate { bytes, file: file.clone(), outcome: CopyOutcome::Skipped })
+                    .expect("Failed to send message through the channel");
+                return;
             }
-            Err(_e) => {
-                _existed_dst_file = false;
+
+            if dst_exists {
+                if let Err(e) = fs::remove_file(&dst_path) {
+                    eprintln!("Failed to replace existing symlink at {:?}: {}", dst_path, e);
+                }
+            }
+
+            match fs::read_link(file).and_then(|link_target| create_symlink(&link_target, &dst_path)) {
+                Ok(_) => {
+                    tx.send(ProgressUpdate { bytes, file: file.clone(), outcome: CopyOutcome::Copied })
+                        .expect("Failed to send message through the channel");
+                }
+                Err(e) => {
+                    eprintln!("Failed to recreate symlink {} => {:?}: {}", file.display(), dst_path, e);
+                    tx.send(ProgressUpdate { bytes: 0, file: file.clone(), outcome: CopyOutcome::Failed })
+                        .expect("Failed to send message through the channel");
+                }
             }
+            return;
+        }
+
+        // Decide whether the destination needs (re)copying based on the conflict mode.
+        let needs_copy = match fs::metadata(&dst_path) {
+            Err(_) => true,
+            Ok(dst_meta) => match fs::metadata(file) {
+                Ok(src_meta) => should_copy(on_conflict, &src_meta, &dst_meta),
+                Err(_) => true,
+            },
         };
 
-        // Skip if destination file is existed already.
-        if _existed_dst_file == false {
+        if needs_copy {
             match fs::copy(&file, &dst_path) {
-                Ok(_) => {tx.send(1).expect("Failed to send message through the channel");}
-                Err(e) => {eprintln!("Failed to copy {} => {:?}: {}", file.display(), dst_path, e);},
-            }            
+                Ok(bytes_copied) => {
+                    if preserve {
+                        if let Ok(src_meta) = fs::metadata(file) {
+                            preserve_timestamps(&src_meta, &dst_path);
+                        }
+                    }
+                    tx.send(ProgressUpdate { bytes: bytes_copied, file: file.clone(), outcome: CopyOutcome::Copied })
+                        .expect("Failed to send message through the channel");
+                }
+                Err(e) => {
+                    eprintln!("Failed to copy {} => {:?}: {}", file.display(), dst_path, e);
+                    tx.send(ProgressUpdate { bytes: 0, file: file.clone(), outcome: CopyOutcome::Failed })
+                        .expect("Failed to send message through the channel");
+                },
+            }
         }
         else {
-            tx.send(1).expect("Failed to send message through the channel");
+            let bytes = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+            tx.send(ProgressUpdate { bytes, file: file.clone(), outcome: CopyOutcome::Skipped })
+                .expect("Failed to send message through the channel");
         }
-    }
+    });
 }
 
 // Function to count the total number of files in a directory
-fn count_files_in_dir(path: &str) -> (u64, u64) {
-    let walkdir = WalkDir::new(path);
+fn count_files_in_dir(path: &str, excludes: &[String], no_ignore: bool) -> (u64, u64) {
     let mut files_count = 0;
     let mut total_size = 0;
 
-    for entry in walkdir.into_iter() {
-        if entry.is_ok() {
-            let dir_entry = entry.unwrap();
-            if dir_entry.file_type().is_file() {
-                files_count += 1;
-                total_size += dir_entry.metadata().unwrap().len();
-            }
+    for dir_entry in walk_filtered(path, excludes, no_ignore) {
+        let file_type = dir_entry.file_type();
+        if file_type.is_file() || file_type.is_symlink() {
+            files_count += 1;
+            total_size += dir_entry.metadata().unwrap().len();
         }
     }
 
@@ -189,10 +460,9 @@ fn count_files_in_dir(path: &str) -> (u64, u64) {
 }
 
 // Function to get a list of all directories in a given path
-fn get_directories(path: &str) -> Vec<PathBuf> {
+fn get_directories(path: &str, excludes: &[String], no_ignore: bool) -> Vec<PathBuf> {
     let mut directories = Vec::new();
-    for entry in WalkDir::new(path) {
-        let entry = entry.unwrap();
+    for entry in walk_filtered(path, excludes, no_ignore) {
         if entry.file_type().is_dir() {
             directories.push(entry.path().to_path_buf());
         }
@@ -201,12 +471,10 @@ fn get_directories(path: &str) -> Vec<PathBuf> {
 }
 
 // Function to get a list of all files in a given path
-fn get_files(path: &str) -> Vec<PathBuf> {
+fn get_files(path: &str, excludes: &[String], no_ignore: bool) -> Vec<PathBuf> {
     let mut files = Vec::new();
-    for entry in WalkDir::new(path)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_file())
+    for entry in walk_filtered(path, excludes, no_ignore)
+        .filter(|e| e.file_type().is_file() || e.file_type().is_symlink())
     {
             files.push(entry.path().to_path_buf());
     }